@@ -0,0 +1,54 @@
+use crate::error::Error;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Size in bytes of the random nonce prepended to each ciphertext
+const NONCE_SIZE: usize = 12;
+
+/// Encrypt `plaintext` with AES-256-GCM under `key`
+///
+/// A fresh random nonce is generated for each call and prepended to
+/// the returned ciphertext so [`decrypt`] can recover it.
+pub(crate) fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+
+    let mut nonce_bytes = [0u8; NONCE_SIZE];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|_| Error::CryptoError)?;
+
+    let mut out = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt data previously produced by [`encrypt`]
+pub(crate) fn decrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < NONCE_SIZE {
+        return Err(Error::CryptoError);
+    }
+    let (nonce_bytes, ciphertext) = data.split_at(NONCE_SIZE);
+
+    let cipher = Aes256Gcm::new(Key::from_slice(key));
+    let nonce = Nonce::from_slice(nonce_bytes);
+
+    cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| Error::CryptoError)
+}
+
+/// Hex-encoded SHA-256 digest of `data`, used as an integrity check
+/// for item files stored with [`crate::Db::collection_with_options`]'s
+/// `integrity` flag enabled
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -4,8 +4,23 @@ use std::io;
 #[derive(Debug)]
 pub enum Error {
     InvalidId,
+    /// A string parsed as a [`crate::Format`] didn't name a format
+    /// this build recognizes
+    InvalidFormat,
+    /// `Collection::restore` found an `Id` that already exists and
+    /// was not told to overwrite it
+    IdAlreadyExists,
     IoError(io::Error),
     JsonError(serde_json::error::Error),
+    CborError(serde_cbor::Error),
+    MessagePackEncodeError(rmp_serde::encode::Error),
+    MessagePackDecodeError(rmp_serde::decode::Error),
+    /// Encryption or decryption of an item failed, e.g. the item was
+    /// corrupted, tampered with, or opened with the wrong key
+    CryptoError,
+    /// An item's on-disk content hash didn't match its recorded
+    /// integrity hash, i.e. the file was corrupted or tampered with
+    IntegrityError,
 }
 
 impl From<io::Error> for Error {
@@ -20,12 +35,37 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+impl From<serde_cbor::Error> for Error {
+    fn from(error: serde_cbor::Error) -> Self {
+        Error::CborError(error)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for Error {
+    fn from(error: rmp_serde::encode::Error) -> Self {
+        Error::MessagePackEncodeError(error)
+    }
+}
+
+impl From<rmp_serde::decode::Error> for Error {
+    fn from(error: rmp_serde::decode::Error) -> Self {
+        Error::MessagePackDecodeError(error)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Error::InvalidId => write!(f, "InvalidId"),
+            Error::InvalidFormat => write!(f, "InvalidFormat"),
+            Error::IdAlreadyExists => write!(f, "IdAlreadyExists"),
             Error::IoError(e) => write!(f, "IoError: {}", e),
             Error::JsonError(e) => write!(f, "JsonError: {}", e),
+            Error::CborError(e) => write!(f, "CborError: {}", e),
+            Error::MessagePackEncodeError(e) => write!(f, "MessagePackEncodeError: {}", e),
+            Error::MessagePackDecodeError(e) => write!(f, "MessagePackDecodeError: {}", e),
+            Error::CryptoError => write!(f, "CryptoError"),
+            Error::IntegrityError => write!(f, "IntegrityError"),
         }
     }
 }
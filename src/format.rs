@@ -0,0 +1,74 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// On-disk serialization format for a collection's items
+///
+/// The format is chosen when a collection is created and recorded in
+/// the collection's metadata so later opens know how to decode the
+/// items it already contains.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Pretty-printed JSON (the default)
+    #[default]
+    Json,
+    /// CBOR binary format
+    Cbor,
+    /// MessagePack binary format
+    MessagePack,
+}
+
+impl Format {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Cbor => "cbor",
+            Format::MessagePack => "messagepack",
+        }
+    }
+
+    pub(crate) fn encode<T, W>(self, writer: &mut W, data: &T) -> Result<(), Error>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        match self {
+            Format::Json => serde_json::to_writer_pretty(writer, data)?,
+            Format::Cbor => serde_cbor::to_writer(writer, data)?,
+            Format::MessagePack => rmp_serde::encode::write(writer, data)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn decode<T, R>(self, reader: R) -> Result<T, Error>
+    where
+        for<'de> T: Deserialize<'de>,
+        R: Read,
+    {
+        Ok(match self {
+            Format::Json => serde_json::from_reader(reader)?,
+            Format::Cbor => serde_cbor::from_reader(reader)?,
+            Format::MessagePack => rmp_serde::decode::from_read(reader)?,
+        })
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "cbor" => Ok(Format::Cbor),
+            "messagepack" => Ok(Format::MessagePack),
+            _ => Err(Error::InvalidFormat),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -1,8 +1,9 @@
-use crate::error::DbError;
+use crate::error::Error;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use serde::{de, ser};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
 use std::fmt;
 
 const ID_SIZE: usize = 16;
@@ -16,30 +17,61 @@ impl Id {
     pub fn random() -> Id {
         let chars = b"0123456789abcdef";
         let mut rng = thread_rng();
-        let mut arr: [u8; ID_SIZE] = Default::default();
+        let mut arr: [u8; ID_SIZE] = [0; ID_SIZE];
         for elem in &mut arr {
             *elem = *chars.choose(&mut rng).unwrap();
         }
         Id(arr)
     }
 
-    /// Convert an ID to a 16-character hexadecimal string
-    pub fn to_str(&self) -> Result<&str, DbError> {
-        std::str::from_utf8(&self.0).map_err(|_| DbError::InvalidId)
+    /// Derive a content-addressed ID from the given bytes
+    ///
+    /// The ID is the first `ID_SIZE / 2` bytes of the SHA-256 digest
+    /// of `bytes`, hex-encoded to the same `ID_SIZE`-character width
+    /// as a random `Id`. Identical content always produces the same
+    /// ID, so inserting the same bytes twice deduplicates to a single
+    /// item.
+    ///
+    /// `Id` is a fixed-size type shared with [`Id::random`], and
+    /// existing on-disk item names and archives produced by
+    /// [`crate::Collection::dump`] are `ID_SIZE`-character strings, so
+    /// this can't widen `ID_SIZE` without breaking every `Id` written
+    /// by an earlier build. The digest is truncated to keep IDs that
+    /// same fixed size; this trades the full 256 bits of SHA-256 for a
+    /// 64-bit collision margin. That's adequate for deduplication
+    /// within a single collection but callers who need stronger
+    /// collision resistance should hash their data themselves and
+    /// store the full digest as a field on the item instead of relying
+    /// on the `Id`.
+    pub fn from_content_hash(bytes: &[u8]) -> Id {
+        let digest = Sha256::digest(bytes);
+        let mut arr: [u8; ID_SIZE] = [0; ID_SIZE];
+        for (i, byte) in digest.iter().take(ID_SIZE / 2).enumerate() {
+            let hex = format!("{:02x}", byte);
+            let hex = hex.as_bytes();
+            arr[i * 2] = hex[0];
+            arr[i * 2 + 1] = hex[1];
+        }
+        Id(arr)
+    }
+
+    /// Convert an ID to a hexadecimal string
+    pub fn to_str(&self) -> Result<&str, Error> {
+        std::str::from_utf8(&self.0).map_err(|_| Error::InvalidId)
     }
 }
 
 impl std::str::FromStr for Id {
-    type Err = DbError;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let b = s.as_bytes();
         if b.len() == ID_SIZE {
-            let mut arr: [u8; ID_SIZE] = Default::default();
+            let mut arr: [u8; ID_SIZE] = [0; ID_SIZE];
             arr.copy_from_slice(b);
             Ok(Id(arr))
         } else {
-            Err(DbError::InvalidId)
+            Err(Error::InvalidId)
         }
     }
 }
@@ -73,7 +105,7 @@ impl<'de> de::Visitor<'de> for IdVisitor {
     type Value = Id;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("a 16-character hexadecimal string")
+        write!(formatter, "a {}-character hexadecimal string", ID_SIZE)
     }
 
     fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
@@ -101,15 +133,34 @@ impl<'de> Deserialize<'de> for Id {
 mod tests {
     use super::*;
 
+    const TEST_ID: &str = "0123456789abcdef";
+
     #[test]
     fn test_id_serialize() {
-        let id = "0123456789abcdef".parse::<Id>().unwrap();
-        assert_eq!(serde_json::to_string(&id).unwrap(), "\"0123456789abcdef\"");
+        let id = TEST_ID[..ID_SIZE].parse::<Id>().unwrap();
+        assert_eq!(
+            serde_json::to_string(&id).unwrap(),
+            format!("\"{}\"", &TEST_ID[..ID_SIZE])
+        );
     }
 
     #[test]
     fn test_id_deserialize() {
-        let id: Id = serde_json::from_str("\"0123456789abcdef\"").unwrap();
-        assert_eq!(id, "0123456789abcdef".parse::<Id>().unwrap());
+        let id: Id = serde_json::from_str(&format!("\"{}\"", &TEST_ID[..ID_SIZE])).unwrap();
+        assert_eq!(id, TEST_ID[..ID_SIZE].parse::<Id>().unwrap());
+    }
+
+    #[test]
+    fn test_from_content_hash_deterministic() {
+        let a = Id::from_content_hash(b"hello world");
+        let b = Id::from_content_hash(b"hello world");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_from_content_hash_differs() {
+        let a = Id::from_content_hash(b"hello world");
+        let b = Id::from_content_hash(b"goodbye world");
+        assert_ne!(a, b);
     }
 }
@@ -0,0 +1,106 @@
+use crate::crypto;
+use crate::error::Error;
+use crate::id::Id;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// On-disk secondary index mapping the values of one JSON field to the
+/// `Id`s of the items that have that value
+///
+/// Indexes are JSON files in the collection root named `.idx-<name>`
+/// (encrypted when the collection has a key, see [`Index::read`]),
+/// read and rewritten in full on every change. This keeps them as
+/// simple as [`crate::metadata::CollectionMetadata`] while still
+/// turning an O(n) directory scan in
+/// [`crate::Collection::find_by_index`] into a single index lookup
+/// followed by reading only the matching item files.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Index {
+    /// Name of the JSON field items are indexed by
+    pub field: String,
+    /// Field value -> IDs of items with that value
+    pub entries: BTreeMap<String, Vec<Id>>,
+}
+
+impl Index {
+    fn path(root: &Path, name: &str) -> PathBuf {
+        root.join(format!(".idx-{}", name))
+    }
+
+    pub(crate) fn new(field: &str) -> Index {
+        Index {
+            field: field.to_string(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Read an index file, decrypting it with `key` if the collection
+    /// has one
+    ///
+    /// Indexed field values can be as sensitive as the item data they
+    /// came from, so an index file is encrypted under the same key as
+    /// items whenever the collection has one.
+    pub(crate) fn read(root: &Path, name: &str, key: Option<&[u8; 32]>) -> Result<Index, Error> {
+        let bytes = fs::read(Self::path(root, name))?;
+        let bytes = match key {
+            Some(key) => crypto::decrypt(key, &bytes)?,
+            None => bytes,
+        };
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Write an index file, encrypting it with `key` if the collection
+    /// has one
+    ///
+    /// See [`Index::read`]. The file is written atomically (see
+    /// [`crate::write_file_atomic`]) so a crash mid-write can never
+    /// leave it truncated and unreadable, which would otherwise break
+    /// [`crate::Collection::find_by_index`] for the whole collection.
+    pub(crate) fn write(
+        &self,
+        root: &Path,
+        name: &str,
+        key: Option<&[u8; 32]>,
+    ) -> Result<(), Error> {
+        let bytes = serde_json::to_vec(self)?;
+        let bytes = match key {
+            Some(key) => crypto::encrypt(key, &bytes)?,
+            None => bytes,
+        };
+        crate::write_file_atomic(root, &Self::path(root, name), &bytes)
+    }
+
+    /// Names of all indexes that exist in `root`
+    pub(crate) fn names(root: &Path) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(root)? {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .expect("failed to convert file name to string");
+            if let Some(stripped) = name.strip_prefix(".idx-") {
+                names.push(stripped.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    pub(crate) fn insert(&mut self, value: &str, id: &Id) {
+        self.entries
+            .entry(value.to_string())
+            .or_default()
+            .push(id.clone());
+    }
+
+    /// Remove `id` from whichever entry currently references it,
+    /// without needing to know its indexed value
+    pub(crate) fn remove(&mut self, id: &Id) {
+        self.entries.retain(|_, ids| {
+            ids.retain(|existing| existing != id);
+            !ids.is_empty()
+        });
+    }
+}
@@ -1,15 +1,141 @@
+mod crypto;
 mod error;
+mod format;
 mod id;
+mod index;
 mod lock;
+mod metadata;
 
 pub use error::Error;
+pub use flate2::Compression;
+pub use format::Format;
 pub use id::Id;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use index::Index;
 use lock::FileLock;
+use metadata::CollectionMetadata;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
+/// Encode `data` with `format`, deflating it if `compression_level` is
+/// set, and encrypting it under `key` if one is given
+///
+/// AES-256-GCM authenticated encryption is used, with a random nonce
+/// generated per call and prepended to the returned bytes.
+fn encode_item<T>(
+    format: Format,
+    compression_level: Option<u32>,
+    key: Option<&[u8; 32]>,
+    data: &T,
+) -> Result<Vec<u8>, Error>
+where
+    T: Serialize,
+{
+    let mut buf = Vec::new();
+    match compression_level {
+        Some(level) => {
+            let mut encoder = DeflateEncoder::new(&mut buf, Compression::new(level));
+            format.encode(&mut encoder, data)?;
+            encoder.finish()?;
+        }
+        None => format.encode(&mut buf, data)?,
+    }
+
+    match key {
+        Some(key) => crypto::encrypt(key, &buf),
+        None => Ok(buf),
+    }
+}
+
+/// Reverse of [`encode_item`]: decrypt, inflate, and decode bytes
+/// previously produced by it
+fn decode_item<T>(
+    format: Format,
+    compression_level: Option<u32>,
+    key: Option<&[u8; 32]>,
+    bytes: &[u8],
+) -> Result<T, Error>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let decrypted;
+    let bytes = match key {
+        Some(key) => {
+            decrypted = crypto::decrypt(key, bytes)?;
+            &decrypted[..]
+        }
+        None => bytes,
+    };
+
+    match compression_level {
+        Some(_) => format.decode(DeflateDecoder::new(bytes)),
+        None => format.decode(bytes),
+    }
+}
+
+/// Path of the sidecar integrity-hash file for an item file
+fn integrity_path(item_path: &Path) -> PathBuf {
+    let mut path = item_path.to_path_buf();
+    path.set_extension("sha256");
+    path
+}
+
+/// Fsync a directory so that changes to its entries (e.g. a rename)
+/// are durable
+fn sync_dir(dir: &Path) -> Result<(), Error> {
+    let dir_file = fs::File::open(dir)?;
+    dir_file.sync_all()?;
+    Ok(())
+}
+
+/// Write `bytes` to `path` atomically and durably
+///
+/// The bytes are written to a uniquely-named temporary file in `dir`,
+/// flushed and fsynced, and then renamed over `path`. Since the
+/// temporary file lives on the same filesystem, the rename is atomic,
+/// so a crash or power loss can never leave `path` half-written. The
+/// containing directory is fsynced as well so the renamed entry is
+/// durable. If anything goes wrong the temporary file is removed
+/// rather than left behind.
+pub(crate) fn write_file_atomic(dir: &Path, path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let tmp_name = format!(".tmp-{}", Id::random());
+    let tmp_path = dir.join(tmp_name);
+
+    let result = (|| -> Result<(), Error> {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        sync_dir(dir)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Extract the value of `field` from `data` as a string suitable for
+/// use as an index key
+///
+/// String fields are indexed by their contents; any other JSON value
+/// (numbers, bools, nested objects) is indexed by its JSON
+/// representation. Returns `None` if `data` has no such field.
+fn field_value<T: Serialize>(data: &T, field: &str) -> Option<String> {
+    let value = serde_json::to_value(data).ok()?;
+    let field_value = value.get(field)?;
+    Some(match field_value.as_str() {
+        Some(s) => s.to_string(),
+        None => field_value.to_string(),
+    })
+}
+
 /// JSON data with its unique ID
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Item<T> {
@@ -28,6 +154,10 @@ impl<T> Item<T> {
 #[derive(Clone, Debug)]
 pub struct Collection {
     root: PathBuf,
+    format: Format,
+    compression_level: Option<u32>,
+    key: Option<[u8; 32]>,
+    integrity: bool,
 }
 
 impl Collection {
@@ -35,6 +165,167 @@ impl Collection {
         Ok(self.root.join(id.to_str()?))
     }
 
+    /// Write `data` to `path` atomically and durably, using the
+    /// collection's format, compression, encryption, and integrity
+    /// settings
+    ///
+    /// See [`write_file_atomic`] for the durability guarantee.
+    ///
+    /// If the collection has integrity checking enabled, a sidecar
+    /// file recording the SHA-256 digest of the written bytes is also
+    /// created, so [`Collection::read_item`] can detect later
+    /// corruption or tampering.
+    fn write_item<T>(&self, path: &Path, data: &T) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        let bytes = encode_item(self.format, self.compression_level, self.key.as_ref(), data)?;
+        write_file_atomic(&self.root, path, &bytes)?;
+        if self.integrity {
+            fs::write(integrity_path(path), crypto::sha256_hex(&bytes))?;
+        }
+        Ok(())
+    }
+
+    /// Read and decode an item file, using the collection's format,
+    /// compression, encryption, and integrity settings
+    ///
+    /// If the collection has integrity checking enabled, the item's
+    /// sidecar hash is recomputed and compared before decoding,
+    /// returning `Error::IntegrityError` on a mismatch.
+    fn read_item<T>(&self, path: &Path) -> Result<T, Error>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let bytes = fs::read(path)?;
+        if self.integrity {
+            let expected = fs::read_to_string(integrity_path(path))?;
+            if crypto::sha256_hex(&bytes) != expected.trim() {
+                return Err(Error::IntegrityError);
+            }
+        }
+        decode_item(
+            self.format,
+            self.compression_level,
+            self.key.as_ref(),
+            &bytes,
+        )
+    }
+
+    // Precondition: an exclusive lock must be taken before calling
+    // this function
+    fn update_indexes<T: Serialize>(&self, id: &Id, data: &T) -> Result<(), Error> {
+        for name in Index::names(&self.root)? {
+            let mut index = Index::read(&self.root, &name, self.key.as_ref())?;
+            index.remove(id);
+            if let Some(value) = field_value(data, &index.field) {
+                index.insert(&value, id);
+            }
+            index.write(&self.root, &name, self.key.as_ref())?;
+        }
+        Ok(())
+    }
+
+    // Precondition: an exclusive lock must be taken before calling
+    // this function
+    fn remove_from_indexes(&self, id: &Id) -> Result<(), Error> {
+        for name in Index::names(&self.root)? {
+            let mut index = Index::read(&self.root, &name, self.key.as_ref())?;
+            index.remove(id);
+            index.write(&self.root, &name, self.key.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Declare a secondary index over the JSON field `field` and build
+    /// it from the collection's current contents
+    ///
+    /// Once created, the index is kept up to date by every method that
+    /// writes to the collection, and can be queried with
+    /// [`Collection::find_by_index`] to avoid scanning every item
+    /// file. Creating an index under a `name` that already exists
+    /// overwrites it.
+    pub fn create_index<T>(&self, name: &str, field: &str) -> Result<(), Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        let mut lock = FileLock::exclusive(&self.root)?;
+        let index = self.scan_index::<T>(field)?;
+        index.write(&self.root, name, self.key.as_ref())?;
+        lock.unlock()?;
+        Ok(())
+    }
+
+    /// Regenerate an index created with [`Collection::create_index`]
+    /// from scratch by scanning every item in the collection
+    ///
+    /// Useful after adopting an index on a collection that already had
+    /// items, or if an index is ever suspected to have diverged from
+    /// the collection's contents.
+    pub fn rebuild_index<T>(&self, name: &str) -> Result<(), Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        let mut lock = FileLock::exclusive(&self.root)?;
+        let field = Index::read(&self.root, name, self.key.as_ref())?.field;
+        let index = self.scan_index::<T>(&field)?;
+        index.write(&self.root, name, self.key.as_ref())?;
+        lock.unlock()?;
+        Ok(())
+    }
+
+    // Precondition: an exclusive lock must be taken before calling
+    // this function
+    fn scan_index<T>(&self, field: &str) -> Result<Index, Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        let mut index = Index::new(field);
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .expect("failed to convert file name to string");
+            let id = match name.parse::<Id>() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if let Ok(val) = self.read_item::<T>(&path) {
+                if let Some(value) = field_value(&val, field) {
+                    index.insert(&value, &id);
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Find items whose `field` (as passed to [`Collection::create_index`])
+    /// equals `value`, using the index `name` instead of scanning every
+    /// item file
+    ///
+    /// `name` must refer to an index already created with
+    /// [`Collection::create_index`].
+    pub fn find_by_index<T>(&self, name: &str, value: &str) -> Result<Vec<Item<T>>, Error>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let mut lock = FileLock::shared(&self.root)?;
+        let index = Index::read(&self.root, name, self.key.as_ref())?;
+        let mut result = Vec::new();
+        if let Some(ids) = index.entries.get(value) {
+            for id in ids {
+                let path = self.item_path(id)?;
+                if let Ok(val) = self.read_item(&path) {
+                    result.push(Item::new(id.clone(), val));
+                }
+            }
+        }
+        lock.unlock()?;
+        Ok(result)
+    }
+
     /// Get all the items in the collection
     pub fn get_all<T>(&self) -> Result<Vec<Item<T>>, Error>
     where
@@ -61,11 +352,12 @@ impl Collection {
                 .file_name()
                 .into_string()
                 .expect("failed to convert file name to string");
-            let id = name.parse::<Id>()?;
+            let id = match name.parse::<Id>() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
             let path = entry.path();
-            let file = fs::File::open(path)?;
-            let reader = io::BufReader::new(file);
-            if let Ok(val) = serde_json::from_reader(reader) {
+            if let Ok(val) = self.read_item(&path) {
                 let item = Item::new(id, val);
                 if f(&item) {
                     result.push(item);
@@ -83,9 +375,7 @@ impl Collection {
     {
         let mut lock = FileLock::shared(&self.root)?;
         let path = self.item_path(id)?;
-        let file = fs::File::open(path)?;
-        let reader = io::BufReader::new(file);
-        let val = serde_json::from_reader(reader)?;
+        let val = self.read_item(&path)?;
         lock.unlock()?;
         Ok(Item::new(id.clone(), val))
     }
@@ -112,9 +402,8 @@ impl Collection {
         let mut lock = FileLock::exclusive(&self.root)?;
         let id = self.gen_id();
         let path = self.item_path(&id)?;
-        let file = fs::File::create(path)?;
-        let writer = io::BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &data)?;
+        self.write_item(&path, &data)?;
+        self.update_indexes(&id, data)?;
         lock.unlock()?;
         Ok(id)
     }
@@ -123,11 +412,38 @@ impl Collection {
     pub fn delete_one(&self, id: &Id) -> Result<(), Error> {
         let mut lock = FileLock::exclusive(&self.root)?;
         let path = self.item_path(id)?;
-        fs::remove_file(path)?;
+        fs::remove_file(&path)?;
+        if self.integrity {
+            let _ = fs::remove_file(integrity_path(&path));
+        }
+        self.remove_from_indexes(id)?;
         lock.unlock()?;
         Ok(())
     }
 
+    /// Insert an item addressed by the content of `data` rather than a
+    /// random ID
+    ///
+    /// The `Id` is derived deterministically from the serialized bytes
+    /// of `data` (see [`Id::from_content_hash`]), so inserting
+    /// identical content twice is a no-op the second time: the
+    /// existing item is left as-is and its `Id` is returned again.
+    pub fn insert_content_addressed<T>(&self, data: &T) -> Result<Id, Error>
+    where
+        T: Serialize,
+    {
+        let mut lock = FileLock::exclusive(&self.root)?;
+        let bytes = serde_json::to_vec(data)?;
+        let id = Id::from_content_hash(&bytes);
+        let path = self.item_path(&id)?;
+        if !path.exists() {
+            self.write_item(&path, data)?;
+            self.update_indexes(&id, data)?;
+        }
+        lock.unlock()?;
+        Ok(id)
+    }
+
     /// Overwrite one item in the collection
     pub fn replace_one<T>(&self, item: &Item<T>) -> Result<(), Error>
     where
@@ -135,9 +451,8 @@ impl Collection {
     {
         let mut lock = FileLock::exclusive(&self.root)?;
         let path = self.item_path(&item.id)?;
-        let file = fs::File::create(path)?;
-        let writer = io::BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &item.data)?;
+        self.write_item(&path, &item.data)?;
+        self.update_indexes(&item.id, &item.data)?;
         lock.unlock()?;
         Ok(())
     }
@@ -155,14 +470,11 @@ impl Collection {
     {
         let mut lock = FileLock::exclusive(&self.root)?;
         let path = self.item_path(id)?;
-        let file = fs::File::open(&path)?;
-        let reader = io::BufReader::new(file);
-        let val = serde_json::from_reader(reader)?;
+        let val = self.read_item(&path)?;
         let mut item = Item::new(id.clone(), val);
         u(&mut item);
-        let file = fs::File::create(&path)?;
-        let writer = io::BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &item.data)?;
+        self.write_item(&path, &item.data)?;
+        self.update_indexes(id, &item.data)?;
         lock.unlock()?;
         Ok(())
     }
@@ -189,28 +501,93 @@ impl Collection {
                 .file_name()
                 .into_string()
                 .expect("failed to convert file name to string");
-            let id = name.parse::<Id>()?;
+            let id = match name.parse::<Id>() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
             let path = entry.path();
-            let file = fs::File::open(&path)?;
-            let reader = io::BufReader::new(file);
-            let val = serde_json::from_reader(reader)?;
+            let val = self.read_item(&path)?;
             let mut item = Item::new(id.clone(), val);
             if f(&item) {
                 u(&mut item);
-                let file = fs::File::create(&path)?;
-                let writer = io::BufWriter::new(file);
-                serde_json::to_writer_pretty(writer, &item.data)?;
+                self.write_item(&path, &item.data)?;
+                self.update_indexes(&id, &item.data)?;
             }
         }
         lock.unlock()?;
         Ok(())
     }
+
+    /// Dump every item in the collection to a single portable archive
+    /// file
+    ///
+    /// The archive is a JSON array of `Item`s and can be loaded back
+    /// with [`Collection::restore`], including into a collection with
+    /// a different [`Format`] or compression setting. This is much
+    /// more convenient than copying a directory of many small item
+    /// files, e.g. for backups or migrating to another machine.
+    ///
+    /// If the collection was opened with [`Db::open_encrypted`], the
+    /// archive is encrypted under the same key, the same way item
+    /// files are; otherwise it's plain JSON. Either way the archive is
+    /// always a single JSON array under the hood, independent of the
+    /// collection's on-disk [`Format`] or compression setting, so it
+    /// can be restored into a collection with different settings.
+    pub fn dump<T>(&self, path: &Path) -> Result<(), Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        let items: Vec<Item<T>> = self.get_all()?;
+        let bytes = serde_json::to_vec_pretty(&items)?;
+        let bytes = match self.key.as_ref() {
+            Some(key) => crypto::encrypt(key, &bytes)?,
+            None => bytes,
+        };
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Restore items from an archive produced by [`Collection::dump`]
+    ///
+    /// If `overwrite` is `false` and an item in the archive has the
+    /// same `Id` as an item already in the collection,
+    /// `Error::IdAlreadyExists` is returned and the restore stops;
+    /// items already written before the conflict was found remain in
+    /// the collection. If `overwrite` is `true`, conflicting items are
+    /// replaced.
+    ///
+    /// If the collection was opened with [`Db::open_encrypted`], the
+    /// archive is expected to be encrypted under the same key, as
+    /// [`Collection::dump`] produces.
+    pub fn restore<T>(&self, path: &Path, overwrite: bool) -> Result<(), Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        let mut lock = FileLock::exclusive(&self.root)?;
+        let bytes = fs::read(path)?;
+        let bytes = match self.key.as_ref() {
+            Some(key) => crypto::decrypt(key, &bytes)?,
+            None => bytes,
+        };
+        let items: Vec<Item<T>> = serde_json::from_slice(&bytes)?;
+        for item in items {
+            let item_path = self.item_path(&item.id)?;
+            if !overwrite && item_path.exists() {
+                return Err(Error::IdAlreadyExists);
+            }
+            self.write_item(&item_path, &item.data)?;
+            self.update_indexes(&item.id, &item.data)?;
+        }
+        lock.unlock()?;
+        Ok(())
+    }
 }
 
 /// Database handle
 #[derive(Clone, Debug)]
 pub struct Db {
     root: PathBuf,
+    key: Option<[u8; 32]>,
 }
 
 impl Db {
@@ -221,16 +598,115 @@ impl Db {
         }
         Ok(Db {
             root: root.to_path_buf(),
+            key: None,
+        })
+    }
+
+    /// Open or create a database with item contents encrypted at rest
+    ///
+    /// Every write path encrypts item contents with AES-256-GCM under
+    /// `key` before they reach disk, and every read path decrypts them
+    /// with the same key. Item filenames (the `Id`s) are not
+    /// encrypted. Databases opened this way are incompatible with
+    /// [`Db::open`]: collections created under one cannot be read
+    /// under the other.
+    pub fn open_encrypted(root: &Path, key: [u8; 32]) -> Result<Db, Error> {
+        if !root.exists() {
+            fs::create_dir_all(root)?;
+        }
+        Ok(Db {
+            root: root.to_path_buf(),
+            key: Some(key),
         })
     }
 
     /// Open or create a collection in the database
+    ///
+    /// New collections default to [`Format::Json`] with no
+    /// compression. Use [`Db::collection_with_format`] or
+    /// [`Db::collection_with_options`] to pick different on-disk
+    /// settings when creating a collection.
     pub fn collection(&self, name: &str) -> Result<Collection, Error> {
+        self.collection_with_options(name, Format::default(), None, false)
+    }
+
+    /// Open or create a collection in the database with a specific
+    /// on-disk [`Format`]
+    ///
+    /// The format only applies when the collection is created; if the
+    /// collection already exists, its stored format is used instead
+    /// and `format` is ignored.
+    pub fn collection_with_format(&self, name: &str, format: Format) -> Result<Collection, Error> {
+        self.collection_with_options(name, format, None, false)
+    }
+
+    /// Open or create a collection in the database with a specific
+    /// on-disk [`Format`], transparent item compression, and optional
+    /// per-item integrity verification
+    ///
+    /// When `compression` is `Some`, each item is deflate-compressed
+    /// on disk at the given level. When `integrity` is `true`, a
+    /// sidecar content-hash file is written alongside each item and
+    /// checked on every read, returning [`Error::IntegrityError`] on a
+    /// mismatch. As with `format`, these settings only apply when the
+    /// collection is created; if the collection already exists, its
+    /// stored settings are used instead and the arguments here are
+    /// ignored.
+    pub fn collection_with_options(
+        &self,
+        name: &str,
+        format: Format,
+        compression: Option<Compression>,
+        integrity: bool,
+    ) -> Result<Collection, Error> {
         let path = self.root.join(name);
-        if !path.exists() {
+        let meta = if path.exists() {
+            CollectionMetadata::read(&path)?
+        } else {
             fs::create_dir(&path)?;
-        }
-        Ok(Collection { root: path })
+            let meta = CollectionMetadata {
+                format,
+                compression_level: compression.map(|c| c.level()),
+                integrity,
+            };
+            meta.write(&path)?;
+            meta
+        };
+        Ok(Collection {
+            root: path,
+            format: meta.format,
+            compression_level: meta.compression_level,
+            key: self.key,
+            integrity: meta.integrity,
+        })
+    }
+
+    /// Dump every item in a collection to a single portable archive
+    /// file
+    ///
+    /// See [`Collection::dump`].
+    pub fn dump<T>(&self, collection_name: &str, path: &Path) -> Result<(), Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        self.collection(collection_name)?.dump::<T>(path)
+    }
+
+    /// Restore items from an archive produced by [`Db::dump`] into a
+    /// collection
+    ///
+    /// See [`Collection::restore`].
+    pub fn restore<T>(
+        &self,
+        collection_name: &str,
+        path: &Path,
+        overwrite: bool,
+    ) -> Result<(), Error>
+    where
+        for<'de> T: Deserialize<'de> + Serialize,
+    {
+        self.collection(collection_name)?
+            .restore::<T>(path, overwrite)
     }
 }
 
@@ -263,4 +739,238 @@ mod tests {
         let val: Item<u32> = conn.get_one(&id).unwrap();
         assert_eq!(val.data, 456);
     }
+
+    #[test]
+    fn test_collection_with_format_round_trip() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+
+        let cbor = db.collection_with_format("cbor", Format::Cbor).unwrap();
+        let id = cbor.insert_one(&123u32).unwrap();
+        let val: Item<u32> = cbor.get_one(&id).unwrap();
+        assert_eq!(val.data, 123);
+
+        let msgpack = db
+            .collection_with_format("msgpack", Format::MessagePack)
+            .unwrap();
+        let id = msgpack.insert_one(&123u32).unwrap();
+        let val: Item<u32> = msgpack.get_one(&id).unwrap();
+        assert_eq!(val.data, 123);
+    }
+
+    #[test]
+    fn test_collection_with_compression_round_trip() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db
+            .collection_with_options(
+                "compressed",
+                Format::default(),
+                Some(Compression::best()),
+                false,
+            )
+            .unwrap();
+        let id = conn.insert_one(&"hello world".to_string()).unwrap();
+        let val: Item<String> = conn.get_one(&id).unwrap();
+        assert_eq!(val.data, "hello world");
+    }
+
+    #[test]
+    fn test_open_encrypted_round_trip() {
+        let dir = tempdir().unwrap();
+        let key = [7u8; 32];
+        let db = Db::open_encrypted(dir.path(), key).unwrap();
+        let conn = db.collection("abc").unwrap();
+        let id = conn.insert_one(&123u32).unwrap();
+        let val: Item<u32> = conn.get_one(&id).unwrap();
+        assert_eq!(val.data, 123);
+
+        // Opening the same on-disk data under the wrong key fails
+        // instead of silently returning garbage.
+        let wrong_key_db = Db::open_encrypted(dir.path(), [9u8; 32]).unwrap();
+        let wrong_key_conn = wrong_key_db.collection("abc").unwrap();
+        let result: Result<Item<u32>, Error> = wrong_key_conn.get_one(&id);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_failed_write_leaves_no_tmp_file_and_preserves_existing_item() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db.collection("abc").unwrap();
+        let id = conn.insert_one(&123u32).unwrap();
+
+        // A map with non-string keys can't be encoded as JSON, so this
+        // write fails while the item is being encoded, before anything
+        // is renamed over the existing item.
+        let mut bad = std::collections::BTreeMap::new();
+        bad.insert(vec![1u8], 1u32);
+        assert!(conn.replace_one(&Item::new(id.clone(), bad)).is_err());
+
+        let val: Item<u32> = conn.get_one(&id).unwrap();
+        assert_eq!(val.data, 123);
+
+        let tmp_files = fs::read_dir(dir.path().join("abc"))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(".tmp-"))
+            .count();
+        assert_eq!(tmp_files, 0);
+    }
+
+    #[test]
+    fn test_dump_and_restore_round_trip() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db.collection("abc").unwrap();
+        let id = conn.insert_one(&123u32).unwrap();
+
+        let archive = dir.path().join("archive.json");
+        conn.dump::<u32>(&archive).unwrap();
+
+        let restore_dir = tempdir().unwrap();
+        let restore_db = Db::open(restore_dir.path()).unwrap();
+        let restore_conn = restore_db.collection("abc").unwrap();
+        restore_conn.restore::<u32>(&archive, false).unwrap();
+        let val: Item<u32> = restore_conn.get_one(&id).unwrap();
+        assert_eq!(val.data, 123);
+
+        // Restoring again without overwrite fails since the item is
+        // already present...
+        assert!(matches!(
+            restore_conn.restore::<u32>(&archive, false),
+            Err(Error::IdAlreadyExists)
+        ));
+        // ...but succeeds with overwrite=true.
+        restore_conn.restore::<u32>(&archive, true).unwrap();
+    }
+
+    #[test]
+    fn test_restore_leaves_earlier_items_in_place_on_conflict() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db.collection("abc").unwrap();
+
+        let existing_id = conn.insert_one(&0u32).unwrap();
+
+        // An archive with one new item followed by one that conflicts
+        // with an item already in the collection.
+        let new_id = Id::random();
+        let items = vec![
+            Item::new(new_id.clone(), 1u32),
+            Item::new(existing_id.clone(), 2u32),
+        ];
+        let archive = dir.path().join("archive.json");
+        fs::write(&archive, serde_json::to_vec(&items).unwrap()).unwrap();
+
+        assert!(matches!(
+            conn.restore::<u32>(&archive, false),
+            Err(Error::IdAlreadyExists)
+        ));
+
+        // The new item was written before the conflict was found, so
+        // it's present...
+        let val: Item<u32> = conn.get_one(&new_id).unwrap();
+        assert_eq!(val.data, 1);
+
+        // ...but the existing item was left untouched since the
+        // restore stopped at the conflict instead of overwriting it.
+        let val: Item<u32> = conn.get_one(&existing_id).unwrap();
+        assert_eq!(val.data, 0);
+    }
+
+    #[test]
+    fn test_insert_content_addressed_dedups() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db.collection("abc").unwrap();
+
+        let id1 = conn.insert_content_addressed(&"hello".to_string()).unwrap();
+        let id2 = conn.insert_content_addressed(&"hello".to_string()).unwrap();
+        assert_eq!(id1, id2);
+
+        // Only one item file was ever written, since the second insert
+        // found the content-addressed ID already in use and left the
+        // existing item as-is.
+        let item_files = fs::read_dir(dir.path().join("abc"))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .count();
+        assert_eq!(item_files, 1);
+    }
+
+    #[test]
+    fn test_integrity_detects_corruption() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db
+            .collection_with_options("abc", Format::default(), None, true)
+            .unwrap();
+        let id = conn.insert_one(&123u32).unwrap();
+
+        // Corrupt the item's bytes on disk without touching its
+        // sidecar hash file.
+        let path = conn.item_path(&id).unwrap();
+        fs::write(&path, "corrupted").unwrap();
+
+        let result: Result<Item<u32>, Error> = conn.get_one(&id);
+        assert!(matches!(result, Err(Error::IntegrityError)));
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    struct Person {
+        name: String,
+    }
+
+    #[test]
+    fn test_find_by_index() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db.collection("people").unwrap();
+        conn.create_index::<Person>("name", "name").unwrap();
+
+        let alice_id = conn
+            .insert_one(&Person {
+                name: "alice".to_string(),
+            })
+            .unwrap();
+        conn.insert_one(&Person {
+            name: "bob".to_string(),
+        })
+        .unwrap();
+
+        let found: Vec<Item<Person>> = conn.find_by_index("name", "alice").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, alice_id);
+
+        conn.update_by_id(&alice_id, |item: &mut Item<Person>| {
+            item.data.name = "carol".to_string();
+        })
+        .unwrap();
+        let found: Vec<Item<Person>> = conn.find_by_index("name", "alice").unwrap();
+        assert!(found.is_empty());
+        let found: Vec<Item<Person>> = conn.find_by_index("name", "carol").unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let dir = tempdir().unwrap();
+        let db = Db::open(dir.path()).unwrap();
+        let conn = db.collection("people").unwrap();
+
+        let id = conn
+            .insert_one(&Person {
+                name: "dave".to_string(),
+            })
+            .unwrap();
+
+        conn.create_index::<Person>("name", "name").unwrap();
+        conn.rebuild_index::<Person>("name").unwrap();
+
+        let found: Vec<Item<Person>> = conn.find_by_index("name", "dave").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, id);
+    }
 }
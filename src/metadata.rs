@@ -0,0 +1,43 @@
+use crate::error::Error;
+use crate::format::Format;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Name of the per-collection metadata file
+pub(crate) const METADATA_FILE_NAME: &str = ".ipjdb";
+
+/// Per-collection configuration recorded on disk so that later opens
+/// read and decode items the same way they were written
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct CollectionMetadata {
+    pub format: Format,
+    /// Deflate compression level (0-9) applied to item contents, or
+    /// `None` if items are stored uncompressed
+    pub compression_level: Option<u32>,
+    /// Whether a sidecar content-hash file is maintained for each
+    /// item so reads can detect corruption or tampering
+    #[serde(default)]
+    pub integrity: bool,
+}
+
+impl CollectionMetadata {
+    /// Read a collection's metadata, defaulting to JSON with no
+    /// compression for collections created before metadata existed
+    pub(crate) fn read(root: &Path) -> Result<CollectionMetadata, Error> {
+        let path = root.join(METADATA_FILE_NAME);
+        if !path.exists() {
+            return Ok(CollectionMetadata::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Write the metadata file atomically (see
+    /// [`crate::write_file_atomic`]) so a crash mid-write can never
+    /// leave it truncated and unreadable on the next open
+    pub(crate) fn write(&self, root: &Path) -> Result<(), Error> {
+        let bytes = serde_json::to_vec(self)?;
+        crate::write_file_atomic(root, &root.join(METADATA_FILE_NAME), &bytes)
+    }
+}